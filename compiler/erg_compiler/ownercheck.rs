@@ -2,7 +2,6 @@ use erg_common::color::{GREEN, RESET};
 use erg_common::dict::Dict;
 use erg_common::error::Location;
 use erg_common::log;
-use erg_common::set::Set;
 use erg_common::traits::{Locational, Stream};
 use erg_common::vis::Visibility;
 use erg_common::Str;
@@ -20,15 +19,252 @@ pub enum WrapperKind {
     Box,
 }
 
-#[derive(Debug, Default)]
+/// A single step in a move path: how to get from a place to one of its
+/// sub-objects. Modeled on rustc's `ProjectionElem`, trimmed to record
+/// fields and tuple indices; array elements have no projection and are
+/// tracked only as part of the whole array.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Projection {
+    Field(Str),
+    Tuple(usize),
+}
+
+/// A place: a base local plus the chain of projections needed to reach one
+/// of its sub-objects, e.g. `x.a` is `{ root: "x", projection: [Field("a")] }`.
+#[derive(Debug, Clone)]
+struct Place {
+    root: Str,
+    projection: Vec<Projection>,
+}
+
+impl Place {
+    fn root(root: Str) -> Self {
+        Place {
+            root,
+            projection: vec![],
+        }
+    }
+
+    /// Whether two places denote the same move path. Borrow conflicts are
+    /// only checked exactly, not through alias/overlap analysis.
+    fn matches(&self, other: &Place) -> bool {
+        self.root == other.root && self.projection == other.projection
+    }
+
+    /// A dotted, human-readable rendering of the place, e.g. `x.a.0`.
+    fn readable(&self) -> Str {
+        let mut s = self.root.to_string();
+        for proj in &self.projection {
+            match proj {
+                Projection::Field(name) => {
+                    s.push('.');
+                    s.push_str(name);
+                }
+                Projection::Tuple(i) => {
+                    s.push('.');
+                    s.push_str(&i.to_string());
+                }
+            }
+        }
+        Str::from(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MoveState {
+    Alive,
+    Moved(Location),
+}
+
+/// A node of a local's move-path tree: whether the place it denotes is
+/// currently alive or moved, plus the sub-paths projected from it. Moving a
+/// node discards the finer-grained state of its children (moving `x` also
+/// moves `x.a`); moving a child leaves its siblings and ancestors alive.
+#[derive(Debug, Clone)]
+struct MovePath {
+    state: MoveState,
+    children: Dict<Projection, MovePath>,
+}
+
+impl MovePath {
+    fn alive() -> Self {
+        MovePath {
+            state: MoveState::Alive,
+            children: Dict::new(),
+        }
+    }
+
+    fn mark_moved(&mut self, projection: &[Projection], moved_loc: Location) {
+        match projection.split_first() {
+            None => {
+                self.state = MoveState::Moved(moved_loc);
+                self.children = Dict::new();
+            }
+            Some((head, rest)) => {
+                let mut child = self.children.remove(head).unwrap_or_else(MovePath::alive);
+                child.mark_moved(rest, moved_loc);
+                self.children.insert(head.clone(), child);
+            }
+        }
+    }
+
+    /// Resurrects the sub-path reached by `projection`, e.g. after a field is
+    /// re-assigned. Does not affect sibling sub-paths.
+    fn resurrect(&mut self, projection: &[Projection]) {
+        match projection.split_first() {
+            None => {
+                self.state = MoveState::Alive;
+                self.children = Dict::new();
+            }
+            Some((head, rest)) => {
+                let mut child = self.children.remove(head).unwrap_or_else(MovePath::alive);
+                child.resurrect(rest);
+                self.children.insert(head.clone(), child);
+            }
+        }
+    }
+
+    /// The location a use of `projection` must be rejected at, if any: the
+    /// place itself may be moved, an ancestor may have been moved wholesale
+    /// (checked while walking down), or, once the target is reached, one of
+    /// its descendants may have been moved out from under it.
+    fn moved_loc(&self, projection: &[Projection]) -> Option<Location> {
+        if let MoveState::Moved(loc) = self.state {
+            return Some(loc);
+        }
+        match projection.split_first() {
+            None => self.any_descendant_moved(),
+            Some((head, rest)) => self
+                .children
+                .get(head)
+                .and_then(|child| child.moved_loc(rest)),
+        }
+    }
+
+    fn any_descendant_moved(&self) -> Option<Location> {
+        for child in self.children.values() {
+            if let MoveState::Moved(loc) = child.state {
+                return Some(loc);
+            }
+            if let Some(loc) = child.any_descendant_moved() {
+                return Some(loc);
+            }
+        }
+        None
+    }
+
+    /// Joins the move state reached by two different arms of a branch (or
+    /// two passes of a loop body): a sub-path moved on either side is
+    /// "maybe-moved" after the join, since a reaching path exists on which it
+    /// is no longer usable.
+    fn merge(&self, other: &MovePath) -> MovePath {
+        let state = match (&self.state, &other.state) {
+            (MoveState::Moved(loc), _) | (_, MoveState::Moved(loc)) => MoveState::Moved(*loc),
+            (MoveState::Alive, MoveState::Alive) => MoveState::Alive,
+        };
+        let mut children = self.children.clone();
+        for (proj, other_child) in other.children.iter() {
+            let merged = match children.get(proj) {
+                Some(self_child) => self_child.merge(other_child),
+                None => other_child.clone(),
+            };
+            children.insert(proj.clone(), merged);
+        }
+        MovePath { state, children }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 struct LocalVars {
-    alive_vars: Set<Str>,
-    dropped_vars: Dict<Str, Location>,
+    locals: Dict<Str, MovePath>,
+    loans: Vec<Loan>,
+}
+
+/// The kind of a live borrow, mirroring `Ownership::Ref`/`Ownership::RefMut`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoanKind {
+    Shared,
+    Mut,
+}
+
+impl LoanKind {
+    fn of(ownership: Ownership) -> Option<Self> {
+        match ownership {
+            Ownership::Ref => Some(LoanKind::Shared),
+            Ownership::RefMut => Some(LoanKind::Mut),
+            Ownership::Owned => None,
+        }
+    }
+
+    /// Whether a new loan of `self`'s kind conflicts with (may not coexist
+    /// with) an already-live loan of `other`'s kind on the same place.
+    fn conflicts_with(&self, other: LoanKind) -> bool {
+        matches!(self, LoanKind::Mut) || matches!(other, LoanKind::Mut)
+    }
+}
+
+/// A live borrow of a place, recorded in the scope it was taken in and
+/// dropped at the end of the statement that took it (see `check_block`).
+#[derive(Debug, Clone)]
+struct Loan {
+    place: Place,
+    kind: LoanKind,
+    loc: Location,
+}
+
+/// Which built-in control-flow procedure a call is to, for the purposes of
+/// flow-sensitive move analysis. erg has no dedicated `if`/`match`/`while`
+/// syntax nodes: they are ordinary calls whose branch/loop bodies are passed
+/// as lambda arguments, so the branching structure has to be recovered from
+/// the callee's name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlFlowKind {
+    Branch,
+    Loop,
+}
+
+/// How a lambda captures a free variable: by reference if every use inside
+/// the body only reads/borrows it, by move if any use would, on its own,
+/// consume the place (an owned position whose type is `mut`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaptureKind {
+    ByRef,
+    ByMove,
+}
+
+impl ControlFlowKind {
+    fn of(name: &str) -> Option<Self> {
+        match name {
+            "if" | "if!" | "match" | "match!" => Some(ControlFlowKind::Branch),
+            "while!" | "for!" => Some(ControlFlowKind::Loop),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct OwnershipChecker {
     path_stack: Vec<(Str, Visibility)>,
+    /// Names of the subroutine scopes currently open, i.e. the
+    /// `Signature::Subr` entries of `path_stack`. Kept separately so
+    /// `suggest_wrapper` can tell "this place shares a name with the
+    /// function directly recursing on it" from an unrelated lambda or
+    /// pattern binding that merely happens to share a name.
+    subr_stack: Vec<Str>,
+    /// How many distinct closures (seen so far in this pass) have captured
+    /// each place by name, used to decide whether a moved-out value is
+    /// actually shared into more than one closure.
+    capture_counts: Dict<Str, usize>,
+    /// The `lambda.id`s already folded into `capture_counts`, so a closure
+    /// that gets visited more than once (e.g. a loop body's cross-iteration
+    /// re-check) only contributes to the count a single time.
+    counted_lambdas: Vec<String>,
+    /// `(scope, place, use site)` triples already reported as a move error,
+    /// so re-checking the same arm from a different starting state (the
+    /// loop body's cross-iteration pass in `check_control_flow`) doesn't
+    /// re-report a move that's already been flagged at the exact same use
+    /// site.
+    reported_moves: Vec<(String, Str, Location)>,
     dict: Dict<Str, LocalVars>,
     errs: OwnershipErrors,
 }
@@ -37,6 +273,10 @@ impl OwnershipChecker {
     pub fn new() -> Self {
         OwnershipChecker {
             path_stack: vec![],
+            subr_stack: vec![],
+            capture_counts: Dict::new(),
+            counted_lambdas: vec![],
+            reported_moves: vec![],
             dict: Dict::new(),
             errs: OwnershipErrors::empty(),
         }
@@ -78,6 +318,245 @@ impl OwnershipChecker {
     fn check_block(&mut self, block: &Block) {
         for chunk in block.iter() {
             self.check_expr(chunk, Ownership::Owned);
+            // A loan only lives for the duration of the statement that took
+            // it; carrying it over to the next statement would false-positive
+            // on ordinary sequential re-borrows of the same place.
+            self.current_scope().loans.clear();
+        }
+    }
+
+    /// Resolves an accessor chain to the place it denotes, e.g. `x.a` becomes
+    /// `{ root: "x", projection: [Field("a")] }`. Returns `None` for
+    /// accessors rooted in something other than a plain local/public name
+    /// (e.g. the result of a call), which field-sensitivity can't help with.
+    fn place_of(expr: &Expr) -> Option<Place> {
+        match expr {
+            Expr::Accessor(Accessor::Local(local)) => Some(Place::root(local.inspect().clone())),
+            Expr::Accessor(Accessor::Public(public)) => Some(Place::root(public.inspect().clone())),
+            Expr::Accessor(Accessor::Attr(attr)) => {
+                let mut place = Self::place_of(&attr.obj)?;
+                place
+                    .projection
+                    .push(Self::projection_of(attr.ident.inspect()));
+                Some(place)
+            }
+            _ => None,
+        }
+    }
+
+    /// A tuple's fields are accessed the same way a record's are (`t.0`), so
+    /// a purely-numeric field name is tracked as a tuple projection instead
+    /// of a record field.
+    fn projection_of(name: &Str) -> Projection {
+        match name.parse::<usize>() {
+            Ok(i) => Projection::Tuple(i),
+            Err(_) => Projection::Field(name.clone()),
+        }
+    }
+
+    /// The name of the function being called, when it's a plain accessor
+    /// (the shape `if`, `match`, `while!`, etc. are called with).
+    fn callee_name(call: &hir::Call) -> Option<&str> {
+        match &call.obj {
+            Expr::Accessor(Accessor::Local(l)) => Some(&l.inspect()[..]),
+            Expr::Accessor(Accessor::Public(p)) => Some(&p.inspect()[..]),
+            _ => None,
+        }
+    }
+
+    /// The lambda arms passed to a branch/loop call: the `then`/`else`
+    /// blocks of an `if`, the arms of a `match`, or the body of a `while!`.
+    /// Kept as whole `Expr::Lambda`s (not just their blocks) so checking one
+    /// goes through the ordinary `Expr::Lambda` handling — params bound in
+    /// their own scope, not leaked into the enclosing one.
+    fn branch_arms(call: &hir::Call) -> Vec<&Expr> {
+        let mut arms = vec![];
+        for parg in call.args.pos_args.iter() {
+            if matches!(parg.expr, Expr::Lambda(_)) {
+                arms.push(&parg.expr);
+            }
+        }
+        for kwarg in call.args.kw_args.iter() {
+            if matches!(kwarg.expr, Expr::Lambda(_)) {
+                arms.push(&kwarg.expr);
+            }
+        }
+        arms
+    }
+
+    /// Every local/public name a lambda body references, paired with
+    /// whether that particular use would, by itself, move the place (an
+    /// owned position whose type is `mut` — the same condition `check_expr`
+    /// uses to decide whether to `drop` it inline). Resolved textually so it
+    /// can be reconciled against the names bound in the lambda's own scope
+    /// rather than assuming every name is local to the lambda.
+    fn free_var_uses(block: &Block) -> Vec<(Str, bool)> {
+        let mut uses = vec![];
+        for chunk in block.iter() {
+            Self::collect_uses(chunk, Ownership::Owned, &mut uses);
+        }
+        uses
+    }
+
+    fn collect_uses(expr: &Expr, ownership: Ownership, uses: &mut Vec<(Str, bool)>) {
+        let consumes = expr.ref_t().is_mut() && ownership.is_owned();
+        match expr {
+            Expr::Accessor(Accessor::Local(local)) => {
+                uses.push((local.inspect().clone(), consumes))
+            }
+            Expr::Accessor(Accessor::Public(public)) => {
+                uses.push((public.inspect().clone(), consumes))
+            }
+            Expr::Accessor(Accessor::Attr(a)) => Self::collect_uses(&a.obj, ownership, uses),
+            Expr::Accessor(_) => {}
+            Expr::Call(call) => {
+                Self::collect_uses(&call.obj, Ownership::Ref, uses);
+                match call.signature_t().unwrap().args_ownership() {
+                    ArgsOwnership::Args {
+                        non_defaults,
+                        defaults,
+                        ..
+                    } => {
+                        let (nd_ownerships, d_ownerships): (Vec<_>, Vec<_>) = non_defaults
+                            .iter()
+                            .enumerate()
+                            .partition(|(i, _)| *i == call.args.pos_args.len());
+                        for (parg, (_, o)) in
+                            call.args.pos_args.iter().zip(nd_ownerships.into_iter())
+                        {
+                            Self::collect_uses(&parg.expr, *o, uses);
+                        }
+                        for (kwarg, (_, o)) in call
+                            .args
+                            .kw_args
+                            .iter()
+                            .zip(d_ownerships.into_iter().chain(defaults.iter().enumerate()))
+                        {
+                            Self::collect_uses(&kwarg.expr, *o, uses);
+                        }
+                    }
+                    ArgsOwnership::VarArgs(o) => {
+                        for parg in call.args.pos_args.iter() {
+                            Self::collect_uses(&parg.expr, o, uses);
+                        }
+                        for kwarg in call.args.kw_args.iter() {
+                            Self::collect_uses(&kwarg.expr, o, uses);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Expr::BinOp(binop) => {
+                Self::collect_uses(&binop.lhs, ownership, uses);
+                Self::collect_uses(&binop.rhs, ownership, uses);
+            }
+            Expr::UnaryOp(unary) => Self::collect_uses(&unary.expr, ownership, uses),
+            Expr::Array(Array::Normal(arr)) => {
+                for a in arr.elems.pos_args.iter() {
+                    Self::collect_uses(&a.expr, ownership, uses);
+                }
+            }
+            Expr::Tuple(Tuple::Normal(arr)) => {
+                for a in arr.elems.pos_args.iter() {
+                    Self::collect_uses(&a.expr, ownership, uses);
+                }
+            }
+            Expr::Dict(hir::Dict::Normal(dic)) => {
+                for a in dic.attrs.kw_args.iter() {
+                    Self::collect_uses(&a.expr, ownership, uses);
+                }
+            }
+            Expr::Record(rec) => {
+                for def in rec.attrs.iter() {
+                    for chunk in def.body.block.iter() {
+                        Self::collect_uses(chunk, ownership, uses);
+                    }
+                }
+            }
+            Expr::Def(def) => {
+                for chunk in def.body.block.iter() {
+                    Self::collect_uses(chunk, ownership, uses);
+                }
+            }
+            // A nested lambda's own body is free-var-collected separately,
+            // when `check_expr` visits it as its own `Expr::Lambda`; recursing
+            // into it here would conflate its params with this lambda's free
+            // variables (its params aren't bound in the outer lambda's
+            // `bound_in_lambda`, see `check_expr`'s `Expr::Lambda` arm).
+            Expr::Lambda(_) => {}
+            _ => {}
+        }
+    }
+
+    fn merge_locals(a: &Dict<Str, MovePath>, b: &Dict<Str, MovePath>) -> Dict<Str, MovePath> {
+        let mut merged = a.clone();
+        for (name, b_path) in b.iter() {
+            let entry = match merged.get(name) {
+                Some(a_path) => a_path.merge(b_path),
+                None => b_path.clone(),
+            };
+            merged.insert(name.clone(), entry);
+        }
+        merged
+    }
+
+    fn merge_dicts(a: &Dict<Str, LocalVars>, b: &Dict<Str, LocalVars>) -> Dict<Str, LocalVars> {
+        let mut merged = a.clone();
+        for (path, b_vars) in b.iter() {
+            let entry = match merged.get(path) {
+                Some(a_vars) => {
+                    let mut loans = a_vars.loans.clone();
+                    loans.extend(b_vars.loans.iter().cloned());
+                    LocalVars {
+                        locals: Self::merge_locals(&a_vars.locals, &b_vars.locals),
+                        loans,
+                    }
+                }
+                None => b_vars.clone(),
+            };
+            merged.insert(path.clone(), entry);
+        }
+        merged
+    }
+
+    /// Flow-sensitive analysis for `if`/`match`/`while!`-shaped calls: each
+    /// arm is checked independently from the same pre-branch snapshot, and
+    /// the post-branch state is the union of what each arm moved (a place
+    /// moved down only one path is still "maybe-moved" afterwards). Loop
+    /// bodies are additionally re-checked against their own post-body state,
+    /// to catch a use that's only a problem on the second iteration.
+    fn check_control_flow(&mut self, call: &hir::Call, kind: ControlFlowKind) {
+        for parg in call.args.pos_args.iter() {
+            if !matches!(parg.expr, Expr::Lambda(_)) {
+                self.check_expr(&parg.expr, Ownership::Owned);
+            }
+        }
+        for kwarg in call.args.kw_args.iter() {
+            if !matches!(kwarg.expr, Expr::Lambda(_)) {
+                self.check_expr(&kwarg.expr, Ownership::Owned);
+            }
+        }
+        let arms = Self::branch_arms(call);
+        if arms.is_empty() {
+            return;
+        }
+        let snapshot = self.dict.clone();
+        let mut joined: Option<Dict<Str, LocalVars>> = None;
+        for arm in &arms {
+            self.dict = snapshot.clone();
+            self.check_expr(arm, Ownership::Owned);
+            joined = Some(match joined {
+                None => self.dict.clone(),
+                Some(acc) => Self::merge_dicts(&acc, &self.dict),
+            });
+        }
+        self.dict = joined.unwrap();
+        if kind == ControlFlowKind::Loop {
+            let post_body = self.dict.clone();
+            for arm in &arms {
+                self.check_expr(arm, Ownership::Owned);
+            }
+            self.dict = Self::merge_dicts(&post_body, &self.dict);
         }
     }
 
@@ -86,6 +565,7 @@ impl OwnershipChecker {
             Expr::Def(def) => {
                 log!("define: {}", def.sig);
                 self.define(def);
+                let is_subr = matches!(&def.sig, Signature::Subr(_));
                 let name = match &def.sig {
                     Signature::Var(var) => {
                         if let Some(name) = var.inspect() {
@@ -96,40 +576,70 @@ impl OwnershipChecker {
                     }
                     Signature::Subr(subr) => subr.ident.inspect().clone(),
                 };
+                if is_subr {
+                    self.subr_stack.push(name.clone());
+                }
                 self.path_stack.push((name, def.sig.vis()));
                 self.dict
                     .insert(Str::from(self.full_path()), LocalVars::default());
+                self.current_scope().loans.clear();
                 self.check_block(&def.body.block);
                 self.path_stack.pop();
+                if is_subr {
+                    self.subr_stack.pop();
+                }
             }
             Expr::Accessor(Accessor::Local(local)) => {
-                self.check_if_dropped(local.inspect(), local.loc());
-                if expr.ref_t().is_mut() && ownership.is_owned() {
+                let place = Place::root(local.inspect().clone());
+                self.check_if_dropped(&place, local.loc(), ownership);
+                if let Some(kind) = LoanKind::of(ownership) {
+                    self.register_loan(&place, kind, local.loc());
+                } else if expr.ref_t().is_mut() && ownership.is_owned() {
                     log!(
                         "drop: {} (in {})",
                         local.inspect(),
                         local.ln_begin().unwrap_or(0)
                     );
-                    self.drop(local.inspect(), expr.loc());
+                    self.drop(&place, expr.loc());
                 }
             }
             Expr::Accessor(Accessor::Public(public)) => {
-                self.check_if_dropped(public.inspect(), public.loc());
-                if expr.ref_t().is_mut() && ownership.is_owned() {
+                let place = Place::root(public.inspect().clone());
+                self.check_if_dropped(&place, public.loc(), ownership);
+                if let Some(kind) = LoanKind::of(ownership) {
+                    self.register_loan(&place, kind, public.loc());
+                } else if expr.ref_t().is_mut() && ownership.is_owned() {
                     log!(
                         "drop: {} (in {})",
                         public.inspect(),
                         public.ln_begin().unwrap_or(0)
                     );
-                    self.drop(public.inspect(), expr.loc());
+                    self.drop(&place, expr.loc());
                 }
             }
             Expr::Accessor(Accessor::Attr(a)) => {
-                // REVIEW: is ownership the same?
-                self.check_expr(&a.obj, ownership)
+                if let Some(place) = Self::place_of(expr) {
+                    self.check_if_dropped(&place, expr.loc(), ownership);
+                    if let Some(kind) = LoanKind::of(ownership) {
+                        self.register_loan(&place, kind, expr.loc());
+                    } else if expr.ref_t().is_mut() && ownership.is_owned() {
+                        self.drop(&place, expr.loc());
+                    }
+                } else {
+                    // REVIEW: is ownership the same?
+                    self.check_expr(&a.obj, ownership)
+                }
             }
             Expr::Accessor(_a) => todo!(),
             // TODO: referenced
+            Expr::Call(call)
+                if Self::callee_name(call)
+                    .and_then(ControlFlowKind::of)
+                    .is_some() =>
+            {
+                let kind = ControlFlowKind::of(Self::callee_name(call).unwrap()).unwrap();
+                self.check_control_flow(call, kind);
+            }
             Expr::Call(call) => {
                 let args_ownership = call.signature_t().unwrap().args_ownership();
                 match args_ownership {
@@ -209,14 +719,69 @@ impl OwnershipChecker {
                     }
                 }
             }
-            // TODO: capturing
             Expr::Lambda(lambda) => {
                 let name_and_vis = (Str::from(format!("<lambda_{}>", lambda.id)), Private);
                 self.path_stack.push(name_and_vis);
                 self.dict
                     .insert(Str::from(self.full_path()), LocalVars::default());
+                for name in lambda.params.inspects() {
+                    self.current_scope()
+                        .locals
+                        .insert(name.clone(), MovePath::alive());
+                }
+                let uses = Self::free_var_uses(&lambda.body);
                 self.check_block(&lambda.body);
+                let bound_in_lambda = self.current_scope().locals.clone();
+                self.current_scope().loans.clear();
                 self.path_stack.pop();
+                // Reconcile the body's free variables against the enclosing
+                // scopes: a name bound inside the lambda (a param or a local
+                // def) isn't a capture at all; everything else is captured
+                // by move if any use of it would, on its own, consume it,
+                // and by reference otherwise.
+                let mut captures: Dict<Str, CaptureKind> = Dict::new();
+                for (name, consumes) in uses {
+                    if bound_in_lambda.get(&name).is_some() {
+                        continue;
+                    }
+                    let kind = if consumes {
+                        CaptureKind::ByMove
+                    } else {
+                        CaptureKind::ByRef
+                    };
+                    let kind = match captures.get(&name) {
+                        Some(CaptureKind::ByMove) => CaptureKind::ByMove,
+                        _ => kind,
+                    };
+                    captures.insert(name, kind);
+                }
+                // Nothing further to mark here: a by-move capture of an outer
+                // place was already moved in its own scope while `check_block`
+                // walked the body above (the ordinary `Expr::Accessor` arms
+                // of this match call `drop`, which searches outer scopes on
+                // its own).
+                // Re-dropping it here, after the lambda's scope is already
+                // popped, would move it a second time and can resolve to the
+                // wrong name if an outer scope happens to share it.
+                // Only fold this closure's captures into `capture_counts` the
+                // first time it's visited: a loop body's cross-iteration
+                // re-check (see `check_control_flow`) visits the same
+                // `Expr::Lambda` a second time, and counting it again would
+                // make a single closure look shared into multiple closures.
+                let lambda_key = lambda.id.to_string();
+                let already_counted = self.counted_lambdas.contains(&lambda_key);
+                if !already_counted {
+                    self.counted_lambdas.push(lambda_key);
+                }
+                for (name, kind) in captures.iter() {
+                    if !already_counted {
+                        let count = self.capture_counts.get(name).copied().unwrap_or(0) + 1;
+                        self.capture_counts.insert(name.clone(), count);
+                    }
+                    if *kind == CaptureKind::ByMove {
+                        log!("capture-move: {name} (in lambda_{})", lambda.id);
+                    }
+                }
             }
             _ => {}
         }
@@ -247,42 +812,122 @@ impl OwnershipChecker {
         match &def.sig {
             Signature::Var(sig) => {
                 for name in sig.pat.inspects() {
-                    self.current_scope().alive_vars.insert(name.clone());
+                    self.define_place(name);
                 }
             }
             Signature::Subr(sig) => {
                 self.current_scope()
-                    .alive_vars
-                    .insert(sig.ident.inspect().clone());
+                    .locals
+                    .insert(sig.ident.inspect().clone(), MovePath::alive());
             }
         }
     }
 
-    fn drop(&mut self, name: &Str, moved_loc: Location) {
+    /// Defines (or re-defines) the place named `name`. A bare name (`x`)
+    /// introduces a fresh whole-variable binding; a dotted name (`x.a`), as
+    /// produced for a `Def` whose signature re-assigns a single field,
+    /// resurrects just that sub-path instead of clobbering the rest of `x`.
+    fn define_place(&mut self, name: &Str) {
+        let mut segments = name.split('.');
+        let root = Str::from(segments.next().unwrap_or(&name[..]));
+        let projection: Vec<Projection> = segments
+            .map(|s| Self::projection_of(&Str::from(s)))
+            .collect();
+        if projection.is_empty() {
+            self.current_scope().locals.insert(root, MovePath::alive());
+        } else if let Some(path) = self.current_scope().locals.get_mut(&root) {
+            path.resurrect(&projection);
+        } else {
+            let mut path = MovePath::alive();
+            path.resurrect(&projection);
+            self.current_scope().locals.insert(root, path);
+        }
+    }
+
+    fn drop(&mut self, place: &Place, moved_loc: Location) {
         for n in 0..self.path_stack.len() {
-            if self.nth_outer_scope(n).alive_vars.remove(name) {
-                self.nth_outer_scope(n)
-                    .dropped_vars
-                    .insert(name.clone(), moved_loc);
+            if let Some(path) = self.nth_outer_scope(n).locals.get_mut(&place.root) {
+                path.mark_moved(&place.projection, moved_loc);
                 return;
             }
         }
-        panic!("variable not found: {name}");
+        panic!("variable not found: {}", place.root);
     }
 
-    fn check_if_dropped(&mut self, name: &Str, loc: Location) {
+    /// Records a new borrow of `place`, reporting a conflict against any
+    /// already-live loan of the same place across the currently open scopes.
+    fn register_loan(&mut self, place: &Place, kind: LoanKind, loc: Location) {
+        let mut conflicting_locs = vec![];
         for n in 0..self.path_stack.len() {
-            if let Some(moved_loc) = self.nth_outer_scope(n).dropped_vars.get(name) {
-                let moved_loc = *moved_loc;
-                self.errs.push(OwnershipError::move_error(
-                    line!() as usize,
-                    name,
-                    loc,
-                    moved_loc,
-                    self.full_path(),
-                ));
+            for loan in self.nth_outer_scope(n).loans.clone() {
+                if loan.place.matches(place) && kind.conflicts_with(loan.kind) {
+                    conflicting_locs.push(loan.loc);
+                }
             }
         }
+        for prior_loc in conflicting_locs {
+            self.errs.push(OwnershipError::borrow_conflict(
+                line!() as usize,
+                &place.readable(),
+                loc,
+                prior_loc,
+                self.full_path(),
+            ));
+        }
+        self.current_scope().loans.push(Loan {
+            place: place.clone(),
+            kind,
+            loc,
+        });
+    }
+
+    fn check_if_dropped(&mut self, place: &Place, loc: Location, ownership: Ownership) {
+        for n in 0..self.path_stack.len() {
+            let moved_loc = self
+                .nth_outer_scope(n)
+                .locals
+                .get(&place.root)
+                .map(|path| path.moved_loc(&place.projection));
+            if let Some(moved_loc) = moved_loc {
+                if let Some(moved_loc) = moved_loc {
+                    let report_key = (self.full_path(), place.readable(), loc);
+                    if !self.reported_moves.contains(&report_key) {
+                        let wrapper = self.suggest_wrapper(place, ownership);
+                        self.errs.push(OwnershipError::move_error(
+                            line!() as usize,
+                            &place.readable(),
+                            loc,
+                            moved_loc,
+                            self.full_path(),
+                            wrapper,
+                        ));
+                        self.reported_moves.push(report_key);
+                    }
+                }
+                return;
+            }
+        }
+    }
+
+    /// Picks a remediation to attach to a move error, following rustc's
+    /// "still used here; consider ..." style. The value being re-used after
+    /// its move needs a different wrapper depending on how it's re-used:
+    /// merely read (`Ref`), actually captured by more than one closure so
+    /// far in this pass (`Rc`, via `capture_counts`), or referenced by a
+    /// function directly recursing on a place of its own name (`Box`, via
+    /// `subr_stack`) — a narrow heuristic that only catches that one direct
+    /// self-reference shape, not recursion mediated through an unrelated
+    /// binding or a type definition this checker has no visibility into.
+    fn suggest_wrapper(&self, place: &Place, use_ownership: Ownership) -> WrapperKind {
+        if !use_ownership.is_owned() {
+            WrapperKind::Ref
+        } else if self.subr_stack.iter().any(|name| name == &place.root) {
+            WrapperKind::Box
+        } else if self.capture_counts.get(&place.root).copied().unwrap_or(0) >= 2 {
+            WrapperKind::Rc
+        } else {
+            WrapperKind::Ref
+        }
     }
 }
 